@@ -0,0 +1,80 @@
+use anyhow::Result;
+use casbin::function_map::OperatorFunction;
+use casbin::{CoreApi, DefaultModel, Enforcer, MgmtApi};
+
+/// Index of the subject field within a policy / request tuple.
+pub const POLICY_FIELD_INDEX_SUBJECT: usize = 0;
+/// Index of the domain (workspace/organization) field. Policies written
+/// before domains existed use [`super::enforcer`]'s wildcard domain here.
+pub const POLICY_FIELD_INDEX_DOMAIN: usize = 1;
+/// Index of the object field.
+pub const POLICY_FIELD_INDEX_OBJECT: usize = 2;
+/// Index of the action field.
+pub const POLICY_FIELD_INDEX_ACTION: usize = 3;
+
+/// The casbin model backing [`AFEnforcer`](super::enforcer::AFEnforcer):
+/// RBAC with domains, so a subject's role/group membership (`g`) is scoped
+/// per-domain instead of being global.
+pub async fn casbin_model() -> Result<DefaultModel> {
+  let model_str = r#"
+[request_definition]
+r = sub, dom, obj, act
+
+[policy_definition]
+p = sub, dom, obj, act
+
+[role_definition]
+g = _, _, _
+
+[policy_effect]
+e = some(where (p.eft == allow))
+
+[matchers]
+m = (r.dom == p.dom || p.dom == "*") && r.obj == p.obj && (g(r.sub, p.sub, p.dom) || r.sub == p.sub) && cmpRoleOrLevel(r.act, p.act)
+"#;
+
+  Ok(DefaultModel::from_str(model_str).await?)
+}
+
+/// Registers the custom `cmpRoleOrLevel` matcher function used by
+/// [`casbin_model`] on a freshly constructed enforcer. Every [`Enforcer`]
+/// handed to [`AFEnforcer::new`](super::enforcer::AFEnforcer::new) needs
+/// this, since casbin resolves matcher function names at `enforce` time.
+pub async fn load_group_policies(enforcer: &mut Enforcer) -> Result<()> {
+  enforcer.add_function("cmpRoleOrLevel", OperatorFunction::Arg2(cmp_role_or_level));
+  enforcer.load_policy().await?;
+  Ok(())
+}
+
+/// Ordinal rank of every act string `cmp_role_or_level` knows how to compare
+/// hierarchically, spanning `AFRole`, `AFAccessLevel`, and the plain
+/// [`Action`](crate::act::Action) variants. A higher rank implies everything
+/// a lower rank does, so e.g. a granted `Member` (30) satisfies a requested
+/// `Write` (30) or `Read` (10), but not a requested `Delete` (50).
+fn act_rank(act: &str) -> Option<u8> {
+  match act {
+    "ReadOnly" | "Read" => Some(10),
+    "ReadAndComment" => Some(20),
+    "ReadAndWrite" | "Write" | "Member" => Some(30),
+    "FullAccess" | "Delete" | "Owner" => Some(50),
+    _ => None,
+  }
+}
+
+/// Compares a requested action against a granted role/access-level string.
+///
+/// Both strings are acts rendered by [`Acts::policy_acts`](crate::act::Acts),
+/// so by the time they reach here they're just strings with no type tag
+/// saying which of `AFRole`/`AFAccessLevel`/`Action` they came from. Rather
+/// than requiring an exact match, `granted_act` is allowed through whenever
+/// its rank in [`act_rank`] is at least `requested_act`'s — that's what lets
+/// a `Member` role satisfy a `Read`/`Write` action or a `ReadOnly`..
+/// `ReadAndWrite` access-level check without the policy needing a separate
+/// row per implied act. Falls back to an exact match for any act string
+/// `act_rank` doesn't recognize.
+pub fn cmp_role_or_level(requested_act: String, granted_act: String) -> bool {
+  match (act_rank(&granted_act), act_rank(&requested_act)) {
+    (Some(granted), Some(requested)) => granted >= requested,
+    _ => requested_act == granted_act,
+  }
+}
@@ -1,23 +1,41 @@
-use super::access::{load_group_policies, POLICY_FIELD_INDEX_OBJECT, POLICY_FIELD_INDEX_SUBJECT};
+use super::access::{
+  load_group_policies, POLICY_FIELD_INDEX_DOMAIN, POLICY_FIELD_INDEX_OBJECT,
+  POLICY_FIELD_INDEX_SUBJECT,
+};
 use crate::act::Acts;
 use crate::entity::{ObjectType, SubjectType};
 use crate::metrics::MetricsCalState;
+use crate::rate_limit::{RateLimitDecision, RateLimiter};
 use crate::request::PolicyRequest;
 use anyhow::anyhow;
 use app_error::AppError;
 use casbin::{CoreApi, Enforcer, MgmtApi};
-use std::sync::atomic::Ordering;
+use moka::sync::Cache;
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::time::sleep;
 use tracing::{event, instrument, trace, warn};
 
-/// Configuration for retry logic with exponential backoff
+/// Default number of `(sub, dom, obj, act)` decisions the decision cache will
+/// memoize before evicting the least-recently-used entry.
+const DEFAULT_ENFORCE_CACHE_CAPACITY: usize = 10_000;
+
+/// Domain used for policies that aren't scoped to a particular
+/// workspace/organization. Matches everything, so a `None` domain passed in
+/// by callers still evaluates against policies written before the `dom`
+/// field existed.
+const WILDCARD_DOMAIN: &str = "*";
+
+/// Configuration for retry logic with decorrelated-jitter backoff
 #[derive(Clone, Debug)]
-struct RetryConfig {
-  /// Initial delay between retries
+pub struct RetryConfig {
+  /// Initial delay between retries, and the floor of the jitter range on
+  /// every subsequent attempt
   pub initial_delay: Duration,
-  /// Maximum delay between retries (cap for exponential backoff)
+  /// Maximum delay between retries (cap for the jitter range)
   pub max_delay: Duration,
   /// Maximum number of retry attempts
   pub max_retries: usize,
@@ -38,32 +56,117 @@ impl Default for RetryConfig {
 
 pub struct AFEnforcer {
   enforcer: RwLock<Enforcer>,
+  // `moka::sync::Cache` is lock-free on the read path (internally sharded,
+  // no `&mut self` needed for `get`/`insert`), so it sits in front of the
+  // `RwLock<Enforcer>` without forcing every `enforce_policy` call — cache
+  // hit or not — onto a single writer. An earlier version of this switched
+  // to casbin's own `CachedEnforcer`, but its cache API requires `&mut
+  // self`, which would have meant taking the *write* lock for every read,
+  // serializing all policy checks server-wide. This keeps the concurrent
+  // reads the prior plain-`Enforcer` code had.
+  decision_cache: Cache<String, bool>,
   pub(crate) metrics_state: MetricsCalState,
+  rate_limiter: Option<Arc<RateLimiter>>,
+  retry_config: RetryConfig,
+  /// Number of times a caller attempted to acquire the write lock, counting
+  /// every retry attempt, not just the initial try.
+  lock_acquisition_attempts: AtomicU64,
+  /// Number of times lock acquisition gave up after exhausting the retry
+  /// budget or the overall timeout.
+  lock_acquisition_timeouts: AtomicU64,
 }
 
 impl AFEnforcer {
-  pub async fn new(mut enforcer: Enforcer) -> Result<Self, AppError> {
+  pub async fn new(enforcer: Enforcer) -> Result<Self, AppError> {
+    Self::new_with_cache_capacity(enforcer, DEFAULT_ENFORCE_CACHE_CAPACITY).await
+  }
+
+  /// Same as [`AFEnforcer::new`], but lets the caller size the decision-cache
+  /// LRU instead of relying on [`DEFAULT_ENFORCE_CACHE_CAPACITY`].
+  pub async fn new_with_cache_capacity(
+    mut enforcer: Enforcer,
+    cache_capacity: usize,
+  ) -> Result<Self, AppError> {
     load_group_policies(&mut enforcer).await?;
     Ok(Self {
       enforcer: RwLock::new(enforcer),
+      decision_cache: Cache::new(cache_capacity as u64),
       metrics_state: MetricsCalState::new(),
+      rate_limiter: None,
+      retry_config: RetryConfig::default(),
+      lock_acquisition_attempts: AtomicU64::new(0),
+      lock_acquisition_timeouts: AtomicU64::new(0),
     })
   }
 
-  /// Retry acquiring a write lock with default configuration
+  /// Same as [`AFEnforcer::new`], but lets the caller tune how aggressively
+  /// the write-lock retry loop backs off (`max_retries`/`timeout` per
+  /// deployment), instead of the built-in [`RetryConfig::default`].
+  pub async fn new_with_retry_config(
+    enforcer: Enforcer,
+    retry_config: RetryConfig,
+  ) -> Result<Self, AppError> {
+    let mut this = Self::new(enforcer).await?;
+    this.retry_config = retry_config;
+    Ok(this)
+  }
+
+  /// Gates [`enforce_policy`](Self::enforce_policy) behind `rate_limiter`,
+  /// keyed by the requesting subject (its uid); the subject's tier sets the
+  /// budget it is checked against.
+  pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+    self.rate_limiter = Some(rate_limiter);
+    self
+  }
+
+  /// Total number of write-lock acquisition attempts made by
+  /// [`retry_write`](Self::retry_write), counting every retry, not just the
+  /// first try. Exposed so contention on the write lock is observable from
+  /// outside this module (e.g. scraped into a gauge/counter by the host
+  /// service) instead of being a dead internal counter.
+  pub fn lock_acquisition_attempts(&self) -> u64 {
+    self.lock_acquisition_attempts.load(Ordering::Relaxed)
+  }
+
+  /// Total number of times [`retry_write`](Self::retry_write) gave up after
+  /// exhausting its retry budget or overall timeout.
+  pub fn lock_acquisition_timeouts(&self) -> u64 {
+    self.lock_acquisition_timeouts.load(Ordering::Relaxed)
+  }
+
+  /// Drops every memoized `(sub, dom, obj, act) -> bool` decision.
+  ///
+  /// Call this whenever the underlying policies change out from under the
+  /// cache in a way that [`update_policy`]/[`remove_policy`] don't already
+  /// cover (e.g. a bulk reload from the adapter).
+  pub async fn clear_cache(&self) -> Result<(), AppError> {
+    self.decision_cache.invalidate_all();
+    Ok(())
+  }
+
+  /// Retry acquiring a write lock with this enforcer's configured
+  /// [`RetryConfig`] (see [`AFEnforcer::new_with_retry_config`])
   async fn retry_write(&self) -> Result<tokio::sync::RwLockWriteGuard<'_, Enforcer>, AppError> {
-    self.retry_write_with_config(RetryConfig::default()).await
+    self
+      .retry_write_with_config(self.retry_config.clone())
+      .await
   }
 
-  /// Retry acquiring a write lock with custom configuration
-  /// Uses exponential backoff with jitter to prevent thundering herd
+  /// Retry acquiring a write lock with custom configuration.
+  ///
+  /// Uses decorrelated-jitter backoff (as described in AWS's "Exponential
+  /// Backoff and Jitter" article): each sleep is drawn uniformly from
+  /// `[initial_delay, previous_sleep * 3]` and capped at `max_delay`. Unlike
+  /// a fixed per-attempt jitter window, this keeps spreading out retries
+  /// from different tasks that happened to start in lockstep, instead of
+  /// letting them re-converge after a few attempts.
   #[instrument(level = "debug", skip_all)]
   async fn retry_write_with_config(
     &self,
     config: RetryConfig,
   ) -> Result<tokio::sync::RwLockWriteGuard<'_, Enforcer>, AppError> {
     let start_time = Instant::now();
-    let mut delay = config.initial_delay;
+    let mut sleep_for = config.initial_delay;
 
     for attempt in 0..config.max_retries {
       // Check if we've exceeded the total timeout
@@ -73,12 +176,19 @@ impl AFEnforcer {
           attempt,
           start_time.elapsed()
         );
+        self
+          .lock_acquisition_timeouts
+          .fetch_add(1, Ordering::Relaxed);
         return Err(AppError::RetryLater(anyhow!(
           "Timeout while acquiring write lock after {} attempts",
           attempt
         )));
       }
 
+      self
+        .lock_acquisition_attempts
+        .fetch_add(1, Ordering::Relaxed);
+
       match self.enforcer.try_write() {
         Ok(guard) => {
           if attempt > 0 {
@@ -92,10 +202,7 @@ impl AFEnforcer {
         },
         Err(_) => {
           if attempt < config.max_retries - 1 {
-            // Add some simple jitter to prevent thundering herd (±10% of delay)
-            let jitter_ms = delay.as_millis() as u64 / 10;
-            let jitter = Duration::from_millis((attempt as u64 * 17) % (jitter_ms.max(1) * 2));
-            let actual_delay = delay + jitter;
+            let actual_delay = decorrelated_jitter(config.initial_delay, sleep_for, config.max_delay);
             trace!(
               "Failed to acquire write lock on attempt {}, retrying after {:?}",
               attempt + 1,
@@ -103,7 +210,7 @@ impl AFEnforcer {
             );
 
             sleep(actual_delay).await;
-            delay = std::cmp::min(delay.saturating_mul(2), config.max_delay);
+            sleep_for = actual_delay;
           }
         },
       }
@@ -114,28 +221,38 @@ impl AFEnforcer {
       config.max_retries,
       start_time.elapsed()
     );
+    self
+      .lock_acquisition_timeouts
+      .fetch_add(1, Ordering::Relaxed);
     Err(AppError::RetryLater(anyhow!("Please try again later")))
   }
 
   /// Update policy for a user.
   /// If the policy is already exist, then it will return Ok(false).
   ///
+  /// `domain` scopes the policy to a workspace/organization id so the same
+  /// subject can hold different roles in different domains. Pass `None` to
+  /// fall back to the wildcard domain, matching policies written before the
+  /// `dom` field existed.
+  ///
   /// [`ObjectType::Workspace`] has to be paired with [`ActionType::Role`],
   /// [`ObjectType::Collab`] has to be paired with [`ActionType::Level`],
   #[instrument(level = "debug", skip_all, err)]
   pub async fn update_policy<T>(
     &self,
     sub: SubjectType,
+    domain: Option<String>,
     obj: ObjectType,
     act: T,
   ) -> Result<(), AppError>
   where
     T: Acts,
   {
+    let domain = domain.unwrap_or_else(|| WILDCARD_DOMAIN.to_string());
     let policies = act
       .policy_acts()
       .into_iter()
-      .map(|act| vec![sub.policy_subject(), obj.policy_object(), act])
+      .map(|act| vec![sub.policy_subject(), domain.clone(), obj.policy_object(), act])
       .collect::<Vec<Vec<_>>>();
 
     trace!("[access control]: add policy:{:?}", policies);
@@ -157,18 +274,41 @@ impl AFEnforcer {
       .await
       .map_err(|e| AppError::Internal(anyhow!("fail to add policy: {e:?}")))?;
 
+    // The policy set changed, so any cached `enforce` decision may now be
+    // stale. The decision cache is keyed by the full (sub, dom, obj, act)
+    // request and doesn't expose per-policy eviction, so invalidate it
+    // wholesale rather than risk serving a decision made under the old
+    // policy set. `enforce_policy` inserts a cache-miss decision while it
+    // still holds the read lock, so no decision evaluated against the old
+    // policy set can be inserted after this invalidation. Cache hits don't
+    // take the enforcer lock, so a hit that lands between the commit above
+    // and this call can still return the old decision.
+    self.decision_cache.invalidate_all();
+    drop(enforcer);
+
     Ok(())
   }
 
   /// Returns policies that match the filter.
+  ///
+  /// `domain` narrows removal to policies scoped to that workspace/org id;
+  /// pass `None` to target the wildcard domain (pre-domain policies).
   pub async fn remove_policy(
     &self,
     sub: SubjectType,
+    domain: Option<String>,
     object_type: ObjectType,
   ) -> Result<(), AppError> {
+    let domain = domain.unwrap_or_else(|| WILDCARD_DOMAIN.to_string());
     let policies_for_user_on_object = {
       let enforcer = self.enforcer.read().await;
-      policies_for_subject_with_given_object(sub.clone(), object_type.clone(), &enforcer).await
+      policies_for_subject_with_given_object(
+        sub.clone(),
+        domain.clone(),
+        object_type.clone(),
+        &enforcer,
+      )
+      .await
     };
 
     event!(
@@ -195,11 +335,20 @@ impl AFEnforcer {
       .await
       .map_err(|e| AppError::Internal(anyhow!("error enforce: {e:?}")))?;
 
+    // See the comment in `update_policy`: invalidate before dropping the
+    // write guard, and invalidate wholesale rather than selectively evict,
+    // since removed policies can flip previously-cached `false` decisions
+    // to `true` (or vice versa) for unrelated requests.
+    self.decision_cache.invalidate_all();
+    drop(enforcer);
+
     Ok(())
   }
 
   /// ## Parameters:
   /// - `uid`: The user ID of the user attempting the action.
+  /// - `domain`: The workspace/organization the request is scoped to, or
+  ///   `None` to evaluate against the wildcard domain.
   /// - `obj`: The type of object being accessed, encapsulated within an `ObjectType`.
   /// - `act`: The action being attempted, encapsulated within an `ActionVariant`.
   ///
@@ -212,6 +361,7 @@ impl AFEnforcer {
   pub async fn enforce_policy<T>(
     &self,
     uid: &i64,
+    domain: Option<String>,
     obj: ObjectType,
     act: T,
   ) -> Result<bool, AppError>
@@ -223,21 +373,70 @@ impl AFEnforcer {
       .total_read_enforce_result
       .fetch_add(1, Ordering::Relaxed);
 
-    let policy_request = PolicyRequest::new(*uid, obj, act);
+    if let Some(rate_limiter) = &self.rate_limiter {
+      let subject = uid.to_string();
+      if let RateLimitDecision::RateLimited { retry_after } = rate_limiter.check(&subject).await {
+        return Err(AppError::RetryLater(anyhow!(
+          "rate limited, retry after {:?}",
+          retry_after
+        )));
+      }
+    }
+
+    let domain = domain.unwrap_or_else(|| WILDCARD_DOMAIN.to_string());
+    let policy_request = PolicyRequest::new(*uid, domain, obj, act);
     let policy = policy_request.to_policy();
-    let result = self
-      .enforcer
-      .read()
-      .await
-      .enforce(policy)
-      .map_err(|e| AppError::Internal(anyhow!("enforce: {e:?}")))?;
+    let cache_key = policy.join(",");
+
+    if let Some(cached) = self.decision_cache.get(&cache_key) {
+      return Ok(cached);
+    }
+
+    // Only a cache miss needs to touch the enforcer at all, and even then
+    // only a shared read lock — unlike casbin's own `enforce_cached`, the
+    // `decision_cache` above doesn't need `&mut Enforcer`, so concurrent
+    // enforce calls (cache hit or miss) no longer contend with each other
+    // behind a single write lock.
+    let result = {
+      let enforcer = self.enforcer.read().await;
+      let result = enforcer
+        .enforce(policy)
+        .map_err(|e| AppError::Internal(anyhow!("enforce: {e:?}")))?;
+      // Insert while still holding the read lock, so a policy write (which
+      // needs the write lock) can't commit and invalidate the cache between
+      // this evaluation and the insert.
+      self.decision_cache.insert(cache_key, result);
+      result
+    };
     Ok(result)
   }
 }
 
+/// `sleep = min(max_delay, random_between(initial_delay, previous_sleep * 3))`
+///
+/// Drawing the next sleep from a range anchored on the *previous* sleep
+/// (rather than a fixed exponential schedule) is what "decorrelates" retries
+/// from tasks that started backing off at the same time.
+fn decorrelated_jitter(
+  initial_delay: Duration,
+  previous_sleep: Duration,
+  max_delay: Duration,
+) -> Duration {
+  let upper = previous_sleep
+    .saturating_mul(3)
+    .max(initial_delay)
+    .min(max_delay);
+  if upper <= initial_delay {
+    return upper;
+  }
+  let jittered_ms = rand::thread_rng().gen_range(initial_delay.as_millis()..=upper.as_millis());
+  Duration::from_millis(jittered_ms as u64).min(max_delay)
+}
+
 #[inline]
 async fn policies_for_subject_with_given_object(
   subject: SubjectType,
+  domain: String,
   object_type: ObjectType,
   enforcer: &Enforcer,
 ) -> Vec<Vec<String>> {
@@ -248,7 +447,9 @@ async fn policies_for_subject_with_given_object(
 
   policies_related_to_object
     .into_iter()
-    .filter(|p| p[POLICY_FIELD_INDEX_SUBJECT] == subject_id)
+    .filter(|p| {
+      p[POLICY_FIELD_INDEX_SUBJECT] == subject_id && p[POLICY_FIELD_INDEX_DOMAIN] == domain
+    })
     .collect::<Vec<_>>()
 }
 
@@ -284,6 +485,7 @@ pub(crate) mod tests {
     enforcer
       .update_policy(
         SubjectType::User(uid),
+        None,
         ObjectType::Workspace(workspace_id.to_string()),
         AFRole::Member,
       )
@@ -295,6 +497,7 @@ pub(crate) mod tests {
       let result = enforcer
         .enforce_policy(
           &uid,
+          None,
           ObjectType::Workspace(workspace_id.to_string()),
           action.clone(),
         )
@@ -305,6 +508,7 @@ pub(crate) mod tests {
     let result = enforcer
       .enforce_policy(
         &uid,
+        None,
         ObjectType::Workspace(workspace_id.to_string()),
         Action::Delete,
       )
@@ -315,6 +519,7 @@ pub(crate) mod tests {
     let result = enforcer
       .enforce_policy(
         &uid,
+        None,
         ObjectType::Workspace(workspace_id.to_string()),
         AFRole::Member,
       )
@@ -325,6 +530,7 @@ pub(crate) mod tests {
     let result = enforcer
       .enforce_policy(
         &uid,
+        None,
         ObjectType::Workspace(workspace_id.to_string()),
         AFRole::Owner,
       )
@@ -340,6 +546,7 @@ pub(crate) mod tests {
       let result = enforcer
         .enforce_policy(
           &uid,
+          None,
           ObjectType::Workspace(workspace_id.to_string()),
           access_level,
         )
@@ -350,6 +557,7 @@ pub(crate) mod tests {
     let result = enforcer
       .enforce_policy(
         &uid,
+        None,
         ObjectType::Workspace(workspace_id.to_string()),
         AFAccessLevel::FullAccess,
       )
@@ -357,4 +565,48 @@ pub(crate) mod tests {
       .expect("enforcing access_level=FullAccess failed");
     assert!(!result, "access_level=FullAccess should not be allowed")
   }
+
+  #[tokio::test]
+  async fn policy_is_scoped_to_domain_test() {
+    let enforcer = test_enforcer().await;
+    let uid = 1;
+    let workspace_id = "w1";
+    let other_workspace_id = "w2";
+
+    // user is a Member, but only within the `w1` domain
+    enforcer
+      .update_policy(
+        SubjectType::User(uid),
+        Some(workspace_id.to_string()),
+        ObjectType::Workspace(workspace_id.to_string()),
+        AFRole::Member,
+      )
+      .await
+      .expect("update policy failed");
+
+    let result = enforcer
+      .enforce_policy(
+        &uid,
+        Some(workspace_id.to_string()),
+        ObjectType::Workspace(workspace_id.to_string()),
+        AFRole::Member,
+      )
+      .await
+      .expect("enforcing in w1 failed");
+    assert!(result, "member should be allowed within its own domain");
+
+    let result = enforcer
+      .enforce_policy(
+        &uid,
+        Some(other_workspace_id.to_string()),
+        ObjectType::Workspace(workspace_id.to_string()),
+        AFRole::Member,
+      )
+      .await
+      .expect("enforcing in w2 failed");
+    assert!(
+      !result,
+      "member of w1 should be denied when the request is scoped to a different domain"
+    );
+  }
 }
@@ -0,0 +1,296 @@
+use anyhow::anyhow;
+use app_error::AppError;
+use dashmap::DashMap;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{trace, warn};
+
+/// How often a subject's local counter is reconciled against the shared
+/// Redis counter. Keeping this above zero is what makes the happy path
+/// cheap: most `check`s only touch the in-process bucket.
+const DEFAULT_RECONCILE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a resolved tier is cached for before being looked up again.
+const TIER_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Outcome of a rate-limit check for a single subject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitDecision {
+  /// The request may proceed.
+  Allowed,
+  /// The subject has exhausted its budget; retry after the given duration.
+  RateLimited { retry_after: Duration },
+  /// The subject's tier could not be resolved: the tier lookup returned
+  /// `None`. Treated as a soft allow. `check` awaits the lookup inline
+  /// whenever the subject's tier isn't cached.
+  UnknownKey,
+}
+
+/// Coarse-grained pricing tier a subject is billed under. Determines the
+/// token-bucket budget applied to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UserTier {
+  Anonymous,
+  Authenticated,
+  Paid,
+}
+
+/// Requests-per-window budget for a [`UserTier`].
+#[derive(Debug, Clone, Copy)]
+struct TierBudget {
+  /// Maximum number of tokens the bucket can hold.
+  capacity: u32,
+  /// Tokens added back per second.
+  refill_per_sec: u32,
+}
+
+impl UserTier {
+  fn budget(self) -> TierBudget {
+    match self {
+      UserTier::Anonymous => TierBudget {
+        capacity: 20,
+        refill_per_sec: 1,
+      },
+      UserTier::Authenticated => TierBudget {
+        capacity: 120,
+        refill_per_sec: 5,
+      },
+      UserTier::Paid => TierBudget {
+        capacity: 600,
+        refill_per_sec: 20,
+      },
+    }
+  }
+}
+
+/// A looked-up-once-and-cached tier for a subject.
+struct CachedTier {
+  tier: UserTier,
+  resolved_at: Instant,
+}
+
+/// In-process token bucket for a single subject. Acts as the fast path so
+/// most `check` calls never round-trip to Redis.
+struct LocalBucket {
+  tokens: f64,
+  budget: TierBudget,
+  last_refill: Instant,
+  last_reconciled: Instant,
+  /// Tokens taken locally since the last Redis reconcile, so that reconcile
+  /// can `INCRBY` the shared counter by what was actually consumed instead
+  /// of leaving it untouched.
+  consumed_since_reconcile: u64,
+}
+
+impl LocalBucket {
+  fn new(budget: TierBudget) -> Self {
+    let now = Instant::now();
+    Self {
+      tokens: budget.capacity as f64,
+      budget,
+      last_refill: now,
+      last_reconciled: now,
+      consumed_since_reconcile: 0,
+    }
+  }
+
+  fn refill(&mut self, now: Instant) {
+    let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+    let replenished = elapsed * self.budget.refill_per_sec as f64;
+    self.tokens = (self.tokens + replenished).min(self.budget.capacity as f64);
+    self.last_refill = now;
+  }
+
+  fn try_take(&mut self) -> bool {
+    let now = Instant::now();
+    self.refill(now);
+    if self.tokens >= 1.0 {
+      self.tokens -= 1.0;
+      self.consumed_since_reconcile += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn retry_after(&self) -> Duration {
+    let missing = (1.0 - self.tokens).max(0.0);
+    let seconds = missing / self.budget.refill_per_sec.max(1) as f64;
+    Duration::from_secs_f64(seconds)
+  }
+}
+
+/// Looks up a subject's [`UserTier`]. Implemented by the host service, which
+/// typically reads this from a user/subscription table.
+#[async_trait::async_trait]
+pub trait TierLookup: Send + Sync {
+  async fn tier_for_subject(&self, subject: &str) -> Option<UserTier>;
+}
+
+/// Deferred/approximate token-bucket rate limiter.
+///
+/// Every [`check`](Self::check) decrements a local, in-process bucket first.
+/// Periodically (every [`DEFAULT_RECONCILE_INTERVAL`]) the local bucket is
+/// reconciled against a counter shared in Redis across server replicas, so
+/// a subject can't exceed its global budget by spreading requests across
+/// instances, while the common case never talks to Redis.
+pub struct RateLimiter {
+  redis: ConnectionManager,
+  tier_lookup: Arc<dyn TierLookup>,
+  local_buckets: DashMap<String, Arc<Mutex<LocalBucket>>>,
+  tier_cache: DashMap<String, CachedTier>,
+  reconcile_interval: Duration,
+}
+
+impl RateLimiter {
+  pub fn new(redis: ConnectionManager, tier_lookup: Arc<dyn TierLookup>) -> Self {
+    Self {
+      redis,
+      tier_lookup,
+      local_buckets: DashMap::new(),
+      tier_cache: DashMap::new(),
+      reconcile_interval: DEFAULT_RECONCILE_INTERVAL,
+    }
+  }
+
+  pub fn with_reconcile_interval(mut self, interval: Duration) -> Self {
+    self.reconcile_interval = interval;
+    self
+  }
+
+  async fn tier_for(&self, subject: &str) -> Option<UserTier> {
+    if let Some(cached) = self.tier_cache.get(subject) {
+      if cached.resolved_at.elapsed() < TIER_CACHE_TTL {
+        return Some(cached.tier);
+      }
+    }
+
+    let tier = self.tier_lookup.tier_for_subject(subject).await?;
+    self.tier_cache.insert(
+      subject.to_string(),
+      CachedTier {
+        tier,
+        resolved_at: Instant::now(),
+      },
+    );
+    Some(tier)
+  }
+
+  /// Checks whether `subject` may perform one more unit of work.
+  pub async fn check(&self, subject: &str) -> RateLimitDecision {
+    let Some(tier) = self.tier_for(subject).await else {
+      return RateLimitDecision::UnknownKey;
+    };
+
+    // Clone the Arc out and drop the DashMap shard guard before taking the
+    // bucket's own lock: `reconcile` below awaits on Redis, and holding a
+    // DashMap shard's internal lock across that await would stall every
+    // other subject hashing into the same shard for the duration of the
+    // round-trip — exactly the contention this local-bucket fast path
+    // exists to avoid.
+    let bucket = self
+      .local_buckets
+      .entry(subject.to_string())
+      .or_insert_with(|| Arc::new(Mutex::new(LocalBucket::new(tier.budget()))))
+      .clone();
+    let mut bucket = bucket.lock().await;
+
+    if bucket.last_reconciled.elapsed() >= self.reconcile_interval {
+      if let Err(e) = self.reconcile(subject, &mut bucket).await {
+        warn!("rate limiter failed to reconcile with redis: {e:?}");
+      }
+      bucket.last_reconciled = Instant::now();
+    }
+
+    if bucket.try_take() {
+      RateLimitDecision::Allowed
+    } else {
+      RateLimitDecision::RateLimited {
+        retry_after: bucket.retry_after(),
+      }
+    }
+  }
+
+  /// Folds this replica's consumption into the shared Redis counter and
+  /// folds the result back into the local bucket, so usage on other
+  /// replicas is reflected here too. The Redis key expires on its own, so a
+  /// quiet subject naturally drops out of the shared counter.
+  async fn reconcile(&self, subject: &str, bucket: &mut LocalBucket) -> Result<(), AppError> {
+    let key = format!("rate_limit:{}", subject);
+    let mut conn = self.redis.clone();
+    let window_secs = 60i64;
+    let consumed = bucket.consumed_since_reconcile as i64;
+
+    // INCRBY the amount *this* replica actually consumed since the last
+    // reconcile (0 is a no-op read if nothing was taken locally), so the
+    // shared counter reflects cluster-wide usage rather than staying flat.
+    let global_count: i64 = conn
+      .incr(&key, consumed)
+      .await
+      .map_err(|e| AppError::Internal(anyhow!("redis incr failed: {e:?}")))?;
+    let _: () = conn
+      .expire(&key, window_secs)
+      .await
+      .map_err(|e| AppError::Internal(anyhow!("redis expire failed: {e:?}")))?;
+    bucket.consumed_since_reconcile = 0;
+
+    let global_capacity = bucket.budget.capacity as i64;
+    if global_count >= global_capacity {
+      bucket.tokens = 0.0;
+      trace!(
+        "rate limiter: subject {} exhausted the shared budget ({}/{})",
+        subject,
+        global_count,
+        global_capacity
+      );
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tiny_budget() -> TierBudget {
+    TierBudget {
+      capacity: 2,
+      refill_per_sec: 1,
+    }
+  }
+
+  #[test]
+  fn local_bucket_denies_once_exhausted() {
+    let mut bucket = LocalBucket::new(tiny_budget());
+
+    assert!(bucket.try_take());
+    assert!(bucket.try_take());
+    assert!(!bucket.try_take(), "bucket should be empty after 2 takes");
+    assert_eq!(bucket.consumed_since_reconcile, 2);
+  }
+
+  #[test]
+  fn local_bucket_refills_over_time() {
+    let mut bucket = LocalBucket::new(tiny_budget());
+    assert!(bucket.try_take());
+    assert!(bucket.try_take());
+    assert!(!bucket.try_take());
+
+    // Simulate 1 second passing without waiting for it in real time.
+    bucket.last_refill -= Duration::from_secs(1);
+    assert!(
+      bucket.try_take(),
+      "a refill_per_sec=1 bucket should regain a token after 1s"
+    );
+  }
+
+  #[test]
+  fn retry_after_is_zero_when_tokens_available() {
+    let bucket = LocalBucket::new(tiny_budget());
+    assert_eq!(bucket.retry_after(), Duration::ZERO);
+  }
+}
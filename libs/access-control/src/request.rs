@@ -0,0 +1,38 @@
+use crate::act::Acts;
+use crate::entity::ObjectType;
+
+/// A single authorization request: "can `uid`, in `domain`, perform `act` on
+/// `obj`?" Rendered via [`to_policy`](Self::to_policy) into the `(sub, dom,
+/// obj, act)` tuple casbin's `[request_definition]` expects.
+pub struct PolicyRequest<T>
+where
+  T: Acts,
+{
+  uid: i64,
+  domain: String,
+  obj: ObjectType,
+  act: T,
+}
+
+impl<T> PolicyRequest<T>
+where
+  T: Acts,
+{
+  pub fn new(uid: i64, domain: String, obj: ObjectType, act: T) -> Self {
+    Self {
+      uid,
+      domain,
+      obj,
+      act,
+    }
+  }
+
+  pub fn to_policy(&self) -> Vec<String> {
+    vec![
+      self.uid.to_string(),
+      self.domain.clone(),
+      self.obj.policy_object(),
+      self.act.policy_acts().into_iter().next().unwrap_or_default(),
+    ]
+  }
+}
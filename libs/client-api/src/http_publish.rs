@@ -1,11 +1,50 @@
 use bytes::Bytes;
 use database_entity::dto::UpdatePublishNamespace;
-use reqwest::Method;
+use reqwest::header::{IF_NONE_MATCH, ETAG};
+use reqwest::{Method, StatusCode};
 use shared_entity::response::{AppResponse, AppResponseError};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
 
+use crate::publish_cache::PublishCache;
+use crate::rate_limit::PublishRateLimiter;
 use crate::Client;
 
+/// Shared across every [`Client`] instance in the process: it only exists to
+/// smooth out local bursts, so there's no reason to give each `Client` its
+/// own bucket.
+fn publish_rate_limiter() -> &'static PublishRateLimiter {
+  static LIMITER: OnceLock<PublishRateLimiter> = OnceLock::new();
+  LIMITER.get_or_init(PublishRateLimiter::new)
+}
+
+fn publish_cache_slot() -> &'static Mutex<Option<PublishCache>> {
+  static CACHE: OnceLock<Mutex<Option<PublishCache>>> = OnceLock::new();
+  CACHE.get_or_init(|| Mutex::new(None))
+}
+
 impl Client {
+  /// Enables the on-disk, content-addressed cache for
+  /// [`get_published_collab_blob`](Self::get_published_collab_blob).
+  ///
+  /// Blobs at least `inline_threshold` bytes are stored as individual
+  /// zstd-compressed files under `dir`; smaller blobs are folded into a
+  /// single sidecar index to avoid tiny-file overhead.
+  pub fn with_publish_cache(self, dir: impl Into<PathBuf>, inline_threshold: usize) -> Self {
+    *publish_cache_slot().lock().unwrap() = Some(PublishCache::new(dir.into(), inline_threshold));
+    self
+  }
+
+  /// Drops every entry from the publish blob cache enabled via
+  /// [`with_publish_cache`](Self::with_publish_cache). A no-op if the cache
+  /// was never enabled.
+  pub fn clear_publish_cache(&self) -> std::io::Result<()> {
+    if let Some(cache) = publish_cache_slot().lock().unwrap().as_ref() {
+      cache.clear()?;
+    }
+    Ok(())
+  }
+
   pub async fn get_workspace_publish_namespace(
     &self,
     workspace_id: &str,
@@ -51,6 +90,8 @@ impl Client {
   where
     T: serde::Serialize,
   {
+    publish_rate_limiter().acquire().await;
+
     let url = format!(
       "{}/api/workspace/{}/publish/{}",
       self.base_url, workspace_id, doc_name
@@ -74,6 +115,8 @@ impl Client {
   where
     T: serde::de::DeserializeOwned,
   {
+    publish_rate_limiter().acquire().await;
+
     let url = format!(
       "{}/api/workspace/published/{}/{}",
       self.base_url, publish_namespace, doc_name
@@ -101,23 +144,51 @@ impl Client {
     publish_namespace: &str,
     doc_name: &str,
   ) -> Result<Bytes, AppResponseError> {
+    publish_rate_limiter().acquire().await;
+
     let url = format!(
       "{}/api/workspace/published/{}/{}/blob",
       self.base_url, publish_namespace, doc_name
     );
-    let bytes = self
-      .cloud_client
-      .get(&url)
-      .send()
-      .await?
-      .error_for_status()?
-      .bytes()
-      .await?;
+
+    let cache_key = PublishCache::cache_key(publish_namespace, doc_name);
+    let cached = publish_cache_slot()
+      .lock()
+      .unwrap()
+      .as_ref()
+      .and_then(|cache| cache.get(&cache_key));
+
+    let mut req = self.cloud_client.get(&url);
+    if let Some((etag, _)) = &cached {
+      req = req.header(IF_NONE_MATCH, etag);
+    }
+
+    let resp = req.send().await?.error_for_status()?;
+
+    if resp.status() == StatusCode::NOT_MODIFIED {
+      if let Some((_, bytes)) = cached {
+        return Ok(Bytes::from(bytes));
+      }
+    }
+
+    let etag = resp
+      .headers()
+      .get(ETAG)
+      .and_then(|value| value.to_str().ok())
+      .map(str::to_string);
+
+    let bytes = resp.bytes().await?;
 
     if let Ok(app_err) = serde_json::from_slice::<AppResponseError>(&bytes) {
       return Err(app_err);
     }
 
+    if let Some(etag) = etag {
+      if let Some(cache) = publish_cache_slot().lock().unwrap().as_ref() {
+        cache.put(&cache_key, &etag, &bytes);
+      }
+    }
+
     Ok(bytes)
   }
 }
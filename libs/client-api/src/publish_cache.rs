@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const INLINE_INDEX_FILE: &str = "inline_index.json";
+
+/// On-disk, content-addressed cache for published collab blobs.
+///
+/// Blobs are compressed with zstd before being persisted. Anything smaller
+/// than `inline_threshold` bytes is folded into a single JSON sidecar
+/// instead of getting its own file, since a directory full of multi-byte
+/// files costs more in inode/metadata overhead than it saves.
+pub(crate) struct PublishCache {
+  dir: PathBuf,
+  inline_threshold: usize,
+  inline_index: Mutex<InlineIndex>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct InlineIndex {
+  entries: HashMap<String, InlineEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct InlineEntry {
+  etag: String,
+  /// zstd-compressed bytes.
+  data: Vec<u8>,
+}
+
+impl PublishCache {
+  pub(crate) fn new(dir: PathBuf, inline_threshold: usize) -> Self {
+    let _ = fs::create_dir_all(&dir);
+    let inline_index = Self::load_inline_index(&dir);
+    Self {
+      dir,
+      inline_threshold,
+      inline_index: Mutex::new(inline_index),
+    }
+  }
+
+  /// Derives the cache key for a published doc from `(publish_namespace,
+  /// doc_name)`. Not cryptographic: both inputs are fully under our control
+  /// and a hash collision only costs a spurious cache miss, never a wrong
+  /// blob being served, since every read is still validated against the
+  /// server's ETag.
+  pub(crate) fn cache_key(publish_namespace: &str, doc_name: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    publish_namespace.hash(&mut hasher);
+    doc_name.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+  }
+
+  /// Returns the cached `(etag, decompressed bytes)` for `key`, if present.
+  pub(crate) fn get(&self, key: &str) -> Option<(String, Vec<u8>)> {
+    if let Some(entry) = self.inline_index.lock().unwrap().entries.get(key) {
+      let bytes = zstd::stream::decode_all(entry.data.as_slice()).ok()?;
+      return Some((entry.etag.clone(), bytes));
+    }
+
+    let compressed = fs::read(self.blob_path(key)).ok()?;
+    let etag = fs::read_to_string(self.etag_path(key)).ok()?;
+    let bytes = zstd::stream::decode_all(compressed.as_slice()).ok()?;
+    Some((etag, bytes))
+  }
+
+  /// Persists `bytes` for `key`, compressed, inline if small enough.
+  pub(crate) fn put(&self, key: &str, etag: &str, bytes: &[u8]) {
+    let compressed = match zstd::stream::encode_all(bytes, 0) {
+      Ok(compressed) => compressed,
+      Err(_) => return,
+    };
+
+    if bytes.len() < self.inline_threshold {
+      let mut index = self.inline_index.lock().unwrap();
+      index.entries.insert(
+        key.to_string(),
+        InlineEntry {
+          etag: etag.to_string(),
+          data: compressed,
+        },
+      );
+      self.persist_inline_index(&index);
+      // An inline entry supersedes any stale on-disk file left over from
+      // before the blob shrank below the threshold.
+      let _ = fs::remove_file(self.blob_path(key));
+      let _ = fs::remove_file(self.etag_path(key));
+    } else {
+      let _ = fs::write(self.blob_path(key), compressed);
+      let _ = fs::write(self.etag_path(key), etag);
+      self.inline_index.lock().unwrap().entries.remove(key);
+      self.persist_inline_index(&self.inline_index.lock().unwrap());
+    }
+  }
+
+  pub(crate) fn clear(&self) -> std::io::Result<()> {
+    *self.inline_index.lock().unwrap() = InlineIndex::default();
+    self.persist_inline_index(&InlineIndex::default());
+
+    if self.dir.exists() {
+      for entry in fs::read_dir(&self.dir)? {
+        let path = entry?.path();
+        if path.file_name().and_then(|n| n.to_str()) != Some(INLINE_INDEX_FILE) {
+          let _ = fs::remove_file(path);
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn blob_path(&self, key: &str) -> PathBuf {
+    self.dir.join(format!("{key}.zst"))
+  }
+
+  fn etag_path(&self, key: &str) -> PathBuf {
+    self.dir.join(format!("{key}.etag"))
+  }
+
+  fn index_path(&self) -> PathBuf {
+    self.dir.join(INLINE_INDEX_FILE)
+  }
+
+  fn load_inline_index(dir: &Path) -> InlineIndex {
+    fs::read(dir.join(INLINE_INDEX_FILE))
+      .ok()
+      .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+      .unwrap_or_default()
+  }
+
+  fn persist_inline_index(&self, index: &InlineIndex) {
+    if let Ok(bytes) = serde_json::to_vec(index) {
+      let _ = fs::write(self.index_path(), bytes);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicU64, Ordering};
+
+  /// A scratch directory under the system temp dir, removed on drop. Every
+  /// test gets its own so `PublishCache`'s on-disk state doesn't leak
+  /// between tests.
+  struct ScratchDir(PathBuf);
+
+  impl ScratchDir {
+    fn new() -> Self {
+      static COUNTER: AtomicU64 = AtomicU64::new(0);
+      let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+      let dir = std::env::temp_dir().join(format!("publish-cache-test-{}-{}", std::process::id(), id));
+      Self(dir)
+    }
+  }
+
+  impl Drop for ScratchDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  #[test]
+  fn cache_key_is_stable_and_distinguishes_inputs() {
+    let a = PublishCache::cache_key("ns1", "doc1");
+    let b = PublishCache::cache_key("ns1", "doc1");
+    let c = PublishCache::cache_key("ns1", "doc2");
+    assert_eq!(a, b, "the same inputs must hash to the same key");
+    assert_ne!(c, a, "different doc names must not collide");
+  }
+
+  #[test]
+  fn put_then_get_round_trips_inline_entries() {
+    let scratch = ScratchDir::new();
+    let cache = PublishCache::new(scratch.0.clone(), 1024);
+    let key = PublishCache::cache_key("ns", "doc");
+    let payload = b"small payload".to_vec();
+
+    cache.put(&key, "etag-1", &payload);
+    let (etag, bytes) = cache.get(&key).expect("entry should be cached");
+    assert_eq!(etag, "etag-1");
+    assert_eq!(bytes, payload);
+  }
+
+  #[test]
+  fn put_then_get_round_trips_file_backed_entries() {
+    let scratch = ScratchDir::new();
+    let cache = PublishCache::new(scratch.0.clone(), 4);
+    let key = PublishCache::cache_key("ns", "doc");
+    let payload = b"this payload is over the inline threshold".to_vec();
+
+    cache.put(&key, "etag-2", &payload);
+    let (etag, bytes) = cache.get(&key).expect("entry should be cached");
+    assert_eq!(etag, "etag-2");
+    assert_eq!(bytes, payload);
+  }
+
+  #[test]
+  fn put_moves_entry_from_file_backed_to_inline_as_it_shrinks() {
+    let scratch = ScratchDir::new();
+    let cache = PublishCache::new(scratch.0.clone(), 8);
+    let key = PublishCache::cache_key("ns", "doc");
+
+    cache.put(&key, "etag-big", b"well over the threshold bytes");
+    assert!(cache.blob_path(&key).exists());
+
+    cache.put(&key, "etag-small", b"tiny");
+    assert!(
+      !cache.blob_path(&key).exists(),
+      "stale on-disk blob should be removed once the entry becomes inline"
+    );
+    let (etag, bytes) = cache.get(&key).expect("entry should still be cached");
+    assert_eq!(etag, "etag-small");
+    assert_eq!(bytes, b"tiny");
+  }
+
+  #[test]
+  fn clear_removes_all_entries() {
+    let scratch = ScratchDir::new();
+    let cache = PublishCache::new(scratch.0.clone(), 8);
+    let inline_key = PublishCache::cache_key("ns", "inline-doc");
+    let file_key = PublishCache::cache_key("ns", "file-doc");
+
+    cache.put(&inline_key, "etag", b"tiny");
+    cache.put(&file_key, "etag", b"well over the threshold bytes");
+
+    cache.clear().expect("clear should succeed");
+
+    assert!(cache.get(&inline_key).is_none());
+    assert!(cache.get(&file_key).is_none());
+  }
+
+  #[test]
+  fn inline_index_survives_reopening_the_cache() {
+    let scratch = ScratchDir::new();
+    let key = PublishCache::cache_key("ns", "doc");
+    {
+      let cache = PublishCache::new(scratch.0.clone(), 1024);
+      cache.put(&key, "etag", b"tiny");
+    }
+
+    let reopened = PublishCache::new(scratch.0.clone(), 1024);
+    let (etag, bytes) = reopened.get(&key).expect("entry should persist across reopen");
+    assert_eq!(etag, "etag");
+    assert_eq!(bytes, b"tiny");
+  }
+}
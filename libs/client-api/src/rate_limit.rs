@@ -0,0 +1,130 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Default burst size and refill rate (requests per second) of the single
+/// token bucket shared by every publish request made from this process;
+/// once it is empty the SDK waits rather than firing another request.
+const DEFAULT_CAPACITY: f64 = 5.0;
+const DEFAULT_REFILL_PER_SEC: f64 = 5.0;
+
+/// Client-side shaping for the publish APIs.
+///
+/// This is deliberately a plain in-process token bucket, not the
+/// Redis-backed limiter the server enforces (see
+/// `access_control::rate_limit::RateLimiter`) — the SDK runs embedded in
+/// desktop/mobile apps with no shared Redis to reconcile against. Its job is
+/// just to keep a single client from hammering the server with bursts that
+/// the server would immediately reject as rate limited; the server remains the source of
+/// truth for the actual limit.
+#[derive(Clone)]
+pub(crate) struct PublishRateLimiter {
+  inner: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+  tokens: f64,
+  capacity: f64,
+  refill_per_sec: f64,
+  last_refill: Instant,
+}
+
+impl PublishRateLimiter {
+  pub(crate) fn new() -> Self {
+    Self {
+      inner: Arc::new(Mutex::new(Bucket {
+        tokens: DEFAULT_CAPACITY,
+        capacity: DEFAULT_CAPACITY,
+        refill_per_sec: DEFAULT_REFILL_PER_SEC,
+        last_refill: Instant::now(),
+      })),
+    }
+  }
+
+  /// Waits until a token is available, then consumes it. Never errors: the
+  /// server is still the one that can reject a request outright.
+  pub(crate) async fn acquire(&self) {
+    loop {
+      let wait = {
+        let mut bucket = self.inner.lock().await;
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * bucket.refill_per_sec).min(bucket.capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+          bucket.tokens -= 1.0;
+          None
+        } else {
+          let missing = 1.0 - bucket.tokens;
+          Some(Duration::from_secs_f64(missing / bucket.refill_per_sec))
+        }
+      };
+
+      match wait {
+        Some(delay) => tokio::time::sleep(delay).await,
+        None => return,
+      }
+    }
+  }
+}
+
+impl Default for PublishRateLimiter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn acquire_does_not_wait_while_tokens_remain() {
+    let limiter = PublishRateLimiter::new();
+
+    // The bucket starts full, so draining it should resolve immediately
+    // rather than hitting the sleep branch.
+    for _ in 0..DEFAULT_CAPACITY as u32 {
+      tokio::time::timeout(Duration::from_millis(50), limiter.acquire())
+        .await
+        .expect("acquire should not wait while the bucket still has tokens");
+    }
+  }
+
+  #[tokio::test]
+  async fn acquire_waits_once_bucket_is_exhausted() {
+    let limiter = PublishRateLimiter::new();
+    for _ in 0..DEFAULT_CAPACITY as u32 {
+      limiter.acquire().await;
+    }
+
+    // The bucket is now empty and refills at DEFAULT_REFILL_PER_SEC tokens/sec,
+    // so the next acquire must wait rather than return immediately.
+    let result = tokio::time::timeout(Duration::from_millis(50), limiter.acquire()).await;
+    assert!(
+      result.is_err(),
+      "acquire should wait once the bucket is exhausted"
+    );
+  }
+
+  #[tokio::test]
+  async fn bucket_refills_over_time() {
+    let bucket = Bucket {
+      tokens: 0.0,
+      capacity: DEFAULT_CAPACITY,
+      refill_per_sec: DEFAULT_REFILL_PER_SEC,
+      last_refill: Instant::now() - Duration::from_secs(1),
+    };
+    let limiter = PublishRateLimiter {
+      inner: Arc::new(Mutex::new(bucket)),
+    };
+
+    // A second's worth of refill at DEFAULT_REFILL_PER_SEC tokens/sec is
+    // enough for at least one token, so this should resolve without waiting
+    // for real time to pass.
+    tokio::time::timeout(Duration::from_millis(50), limiter.acquire())
+      .await
+      .expect("acquire should succeed once the simulated elapsed time refills a token");
+  }
+}